@@ -47,19 +47,36 @@ macro_rules! sequential_id_alloc {
 pub struct $ty<T = $output_ty, A = $crate::BitArr![for $max - 1, in $arr_ty]> {
             next_ptr: usize,
             bits: A,
+            /// Offset added to bitmap indices to produce ids, so the public range
+            /// is `[base, base + $max)` instead of always starting at 0.
+            base: usize,
             _output_type: std::marker::PhantomData<T>,
         }
 
         impl<T> Default for $ty<T> {
             fn default() -> Self {
+                let mut bits: $crate::BitArr![for $max - 1, in $arr_ty] = Default::default();
+
+                // Bits at or beyond `$max` only exist as padding to fill out the last
+                // storage word. Mark them permanently allocated so they are never
+                // handed out by `alloc`.
+                for padding in $max..bits.len() {
+                    bits.set(padding, true);
+                }
+
                 Self {
                     next_ptr: Default::default(),
-                    bits: Default::default(),
+                    bits,
+                    base: 0,
                     _output_type: Default::default(),
                 }
             }
         }
 
+        // Not every instantiation of this macro (e.g. in tests) exercises the
+        // whole generated API, so allow methods that happen to go unused on a
+        // given `$ty` without tripping `-D warnings` builds.
+        #[allow(dead_code)]
         impl<T> $ty<T>
         where
             T: Into<usize>,
@@ -69,39 +86,298 @@ pub struct $ty<T = $output_ty, A = $crate::BitArr![for $max - 1, in $arr_ty]> {
                 $max
             }
 
+            /// Creates an allocator whose ids span the inclusive range `[min, min + $max - 1]`
+            /// instead of starting at 0, for callers that must avoid some reserved
+            /// low ids (e.g. a VM resource allocator).
+            pub fn with_range(min: usize) -> Self {
+                Self {
+                    base: min,
+                    ..Self::default()
+                }
+            }
+
             pub fn alloc(&mut self) -> Option<T> {
-                let index = self
-                    .bits
-                    .into_iter()
-                    .enumerate()
-                    .cycle()
-                    .skip(self.next_ptr)
-                    .take($max)
-                    .filter_map(|(i, b)| if !b { Some(i) } else { None })
-                    .next()?;
+                // Scan word-by-word (instead of bit-by-bit) for the first free bit
+                // from `next_ptr`, wrapping around to the start once the end of the
+                // backing storage is reached. This is O(MAX / word_bits) instead of
+                // the O(MAX) cost of walking every individual bit.
+                const BITS: usize = $arr_ty::BITS as usize;
+
+                let words = self.bits.as_raw_slice();
+                let num_words = words.len();
+                let next_ptr = self.next_ptr % (num_words * BITS);
+                let start_word = next_ptr / BITS;
+                let start_bit = next_ptr % BITS;
+
+                let low_mask = <$arr_ty>::MAX << start_bit;
+                let index = if let Some(free) = Self::first_free_bit(!words[start_word] & low_mask) {
+                    start_word * BITS + free
+                } else {
+                    let wrapped = (1..num_words)
+                        .map(|offset| (start_word + offset) % num_words)
+                        .find_map(|word_idx| {
+                            Self::first_free_bit(!words[word_idx]).map(|free| word_idx * BITS + free)
+                        });
+
+                    match wrapped {
+                        Some(index) => index,
+                        None => {
+                            start_word * BITS
+                                + Self::first_free_bit(!words[start_word] & !low_mask)?
+                        }
+                    }
+                };
 
                 self.bits.set(index, true);
                 self.next_ptr = index + 1;
 
-                T::try_from(index).ok()
+                T::try_from(index + self.base).ok()
+            }
+
+            fn first_free_bit(masked_word: $arr_ty) -> Option<usize> {
+                if masked_word == 0 {
+                    None
+                } else {
+                    Some(masked_word.trailing_zeros() as usize)
+                }
+            }
+
+            /// Translates a public id into a bitmap index, or `None` if it falls
+            /// outside `[base, base + $max)`.
+            fn to_index(&self, id: usize) -> Option<usize> {
+                let index = id.checked_sub(self.base)?;
+                (index < $max).then_some(index)
             }
 
             pub fn dealloc(&mut self, id: T) {
                 let id = id.into();
-                self.bits.set(id, false);
+                if let Some(index) = self.to_index(id) {
+                    self.bits.set(index, false);
+                }
+            }
+
+            /// Claims a specific `id`, e.g. one handed out by some other authority,
+            /// so that this allocator never hands it out again.
+            ///
+            /// Returns `true` if the id was free and is now reserved, or `false`
+            /// if it was already taken or falls outside this allocator's range.
+            pub fn reserve(&mut self, id: T) -> bool {
+                let id = id.into();
+                match self.to_index(id) {
+                    Some(index) if !self.bits[index] => {
+                        self.bits.set(index, true);
+                        true
+                    }
+                    _ => false,
+                }
+            }
+
+            /// Marks `id` as allocated by an external source and advances
+            /// `next_ptr` past it if necessary, so a subsequent `alloc()` won't
+            /// immediately hand back an id that's already in use elsewhere.
+            pub fn mark_external_id(&mut self, id: T) {
+                let id = id.into();
+                if let Some(index) = self.to_index(id) {
+                    self.bits.set(index, true);
+                    if index >= self.next_ptr {
+                        self.next_ptr = index + 1;
+                    }
+                }
             }
 
             pub fn contains(&self, id: T) -> bool {
                 let id = id.into();
-                self.bits[id]
+                match self.to_index(id) {
+                    Some(index) => self.bits[index],
+                    None => false,
+                }
+            }
+
+            pub fn is_full(&self) -> bool {
+                self.bits[..$max].count_zeros() == 0
+            }
+
+            pub fn size(&self) -> usize {
+                // Padding bits beyond `$max` are permanently set (see `Default`)
+                // and must not be counted as real allocations.
+                self.bits[..$max].count_ones()
+            }
+
+            /// Iterates over all currently allocated ids, in ascending order.
+            pub fn iter_allocated(&self) -> impl Iterator<Item = T> + '_ {
+                let base = self.base;
+                self.bits
+                    .iter_ones()
+                    .take_while(|&index| index < $max)
+                    .filter_map(move |index| T::try_from(index + base).ok())
+            }
+
+            /// Iterates over all currently free ids, in ascending order. Padding
+            /// bits beyond `$max` are never reported as free.
+            pub fn iter_free(&self) -> impl Iterator<Item = T> + '_ {
+                let base = self.base;
+                self.bits
+                    .iter_zeros()
+                    .take_while(|&index| index < $max)
+                    .filter_map(move |index| T::try_from(index + base).ok())
+            }
+
+            /// Finds and reserves `n` consecutive free ids, returning the first
+            /// one of the run. Scanning continues the sequential-from-`next_ptr`
+            /// discipline of `alloc`, wrapping around to the start of the id
+            /// space like `alloc` does. `None` is returned if no run of length
+            /// `n` exists anywhere in the id space.
+            pub fn alloc_contiguous(&mut self, n: usize) -> Option<T> {
+                if n == 0 || n > $max {
+                    return None;
+                }
+
+                let mut run_start = 0;
+                let mut run_len = 0;
+                let mut prev_index = None;
+
+                // After wrapping, scan the whole id space again so that a free run
+                // straddling `next_ptr` is found as well.
+                for index in (self.next_ptr..$max).chain(0..$max) {
+                    // A run can't span the wrap-around seam: id `$max - 1` and
+                    // id `0` aren't actually adjacent.
+                    if prev_index != Some(index.wrapping_sub(1)) {
+                        run_len = 0;
+                    }
+                    prev_index = Some(index);
+
+                    if self.bits[index] {
+                        run_len = 0;
+                        continue;
+                    }
+
+                    if run_len == 0 {
+                        run_start = index;
+                    }
+                    run_len += 1;
+
+                    if run_len == n {
+                        for id in run_start..run_start + n {
+                            self.bits.set(id, true);
+                        }
+                        self.next_ptr = run_start + n;
+                        return T::try_from(run_start + self.base).ok();
+                    }
+                }
+
+                None
+            }
+
+            /// Frees the `n` ids starting at `start`. Does nothing if `start` falls
+            /// outside `[base, base + $max)`, same as `dealloc`; ids of the run past
+            /// the end of that range are silently ignored.
+            pub fn dealloc_contiguous(&mut self, start: T, n: usize) {
+                let start = start.into();
+                if let Some(index) = self.to_index(start) {
+                    for id in index..index.saturating_add(n).min($max) {
+                        self.bits.set(id, false);
+                    }
+                }
+            }
+        }
+    };
+}
+
+/// Generates a thread-safe companion of [`sequential_id_alloc`]: the full API
+/// of the generated allocator (`alloc`, `dealloc`, `contains`, `size`,
+/// `is_full`, `reserve`, `mark_external_id`, `alloc_contiguous`,
+/// `dealloc_contiguous`, `iter_allocated`, `iter_free`, `with_range`), but
+/// taking `&self` instead of `&mut self` by wrapping the allocator in a
+/// [`std::sync::Mutex`]. This lets the allocator be shared across threads as
+/// an `Arc<...>` without every caller hand-rolling the locking (and getting
+/// poison recovery wrong).
+#[macro_export]
+macro_rules! sequential_id_alloc_sync {
+    ($ty:ident, $inner:ident, $output_ty:ident, $max:expr, $arr_ty:ident) => {
+        $crate::sequential_id_alloc!($inner, $output_ty, $max, $arr_ty);
+
+        #[derive(Debug, Default)]
+        pub struct $ty<T = $output_ty> {
+            inner: std::sync::Mutex<$inner<T>>,
+        }
+
+        // Same rationale as on the inner type's impl: unused methods on a
+        // particular `$ty` shouldn't fail `-D warnings` builds.
+        #[allow(dead_code)]
+        impl<T> $ty<T>
+        where
+            T: Into<usize>,
+            T: TryFrom<usize>,
+        {
+            pub const fn max() -> usize {
+                $max
+            }
+
+            /// Creates an allocator whose ids span the inclusive range `[min, min + $max - 1]`,
+            /// same as the plain allocator's `with_range`.
+            pub fn with_range(min: usize) -> Self {
+                Self {
+                    inner: std::sync::Mutex::new($inner::with_range(min)),
+                }
+            }
+
+            fn lock(&self) -> std::sync::MutexGuard<'_, $inner<T>> {
+                self.inner
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+            }
+
+            pub fn alloc(&self) -> Option<T> {
+                self.lock().alloc()
+            }
+
+            pub fn dealloc(&self, id: T) {
+                self.lock().dealloc(id)
+            }
+
+            /// See the plain allocator's `reserve`.
+            pub fn reserve(&self, id: T) -> bool {
+                self.lock().reserve(id)
+            }
+
+            /// See the plain allocator's `mark_external_id`.
+            pub fn mark_external_id(&self, id: T) {
+                self.lock().mark_external_id(id)
+            }
+
+            pub fn contains(&self, id: T) -> bool {
+                self.lock().contains(id)
             }
 
             pub fn is_full(&self) -> bool {
-                self.bits.count_zeros() == 0
+                self.lock().is_full()
             }
 
             pub fn size(&self) -> usize {
-                self.bits.count_ones()
+                self.lock().size()
+            }
+
+            /// See the plain allocator's `alloc_contiguous`.
+            pub fn alloc_contiguous(&self, n: usize) -> Option<T> {
+                self.lock().alloc_contiguous(n)
+            }
+
+            /// See the plain allocator's `dealloc_contiguous`.
+            pub fn dealloc_contiguous(&self, start: T, n: usize) {
+                self.lock().dealloc_contiguous(start, n)
+            }
+
+            /// See the plain allocator's `iter_allocated`. Returns a snapshot
+            /// rather than a live view, since the lock can't be held for the
+            /// lifetime of a borrowed iterator.
+            pub fn iter_allocated(&self) -> impl Iterator<Item = T> {
+                self.lock().iter_allocated().collect::<Vec<_>>().into_iter()
+            }
+
+            /// See the plain allocator's `iter_free`. Returns a snapshot
+            /// rather than a live view, for the same reason as `iter_allocated`.
+            pub fn iter_free(&self) -> impl Iterator<Item = T> {
+                self.lock().iter_free().collect::<Vec<_>>().into_iter()
             }
         }
     };
@@ -158,6 +434,226 @@ mod tests {
         assert_eq!(ids.alloc(), Some(5));
     }
 
+    sequential_id_alloc!(SequentialIdAllocU10, u8, 10, u32);
+
+    #[test]
+    fn test_non_word_aligned_max() {
+        // $max (10) is not a multiple of the u32 word width, so the backing
+        // storage has padding bits beyond index 9 that must never be handed out.
+        let mut ids = SequentialIdAllocU10::<u8>::default();
+        for _ in 0..SequentialIdAllocU10::<u8>::max() {
+            let id = ids.alloc().expect("should allocate within range");
+            assert!((id as usize) < SequentialIdAllocU10::<u8>::max());
+        }
+
+        assert!(ids.is_full());
+        assert!(ids.alloc().is_none());
+
+        ids.dealloc(3);
+        assert_eq!(ids.alloc(), Some(3));
+    }
+
+    #[test]
+    fn test_non_word_aligned_max_size_excludes_padding() {
+        // A fresh allocator must report size 0, not the count of the 22
+        // permanently-set padding bits past index 9.
+        let ids = SequentialIdAllocU10::<u8>::default();
+        assert_eq!(ids.size(), 0);
+        assert!(!ids.is_full());
+    }
+
+    #[test]
+    fn test_reserve() {
+        let mut ids = SequentialIdAllocU8::<u8>::default();
+
+        assert!(ids.reserve(5));
+        assert!(ids.contains(5));
+        assert!(!ids.reserve(5));
+
+        assert_eq!(ids.alloc(), Some(0));
+        assert_eq!(ids.alloc(), Some(1));
+    }
+
+    #[test]
+    fn test_mark_external_id() {
+        let mut ids = SequentialIdAllocU8::<u8>::default();
+
+        assert_eq!(ids.alloc(), Some(0));
+
+        ids.mark_external_id(10);
+        assert!(ids.contains(10));
+        assert_eq!(ids.alloc(), Some(11));
+
+        // Marking an id behind next_ptr must not move next_ptr backwards.
+        ids.mark_external_id(3);
+        assert!(ids.contains(3));
+        assert_eq!(ids.alloc(), Some(12));
+    }
+
+    // $output_ty must satisfy `Into<usize>`, which std only implements for
+    // u8/u16 (not u32/u64), so the range tests use u16 ids over a u32-backed
+    // bitmap rather than u32 ids.
+    sequential_id_alloc!(SequentialIdAllocU16Range, u16, 100, u32);
+
+    #[test]
+    fn test_with_range() {
+        let mut ids = SequentialIdAllocU16Range::<u16>::with_range(1000);
+
+        assert_eq!(ids.alloc(), Some(1000));
+        assert_eq!(ids.alloc(), Some(1001));
+        assert!(ids.contains(1000));
+        assert!(!ids.contains(0));
+
+        ids.dealloc(1000);
+        assert!(!ids.contains(1000));
+        assert_eq!(ids.alloc(), Some(1002));
+    }
+
+    #[test]
+    fn test_with_range_out_of_bounds_is_safe() {
+        let mut ids = SequentialIdAllocU16Range::<u16>::with_range(1000);
+
+        // Ids outside [1000, 1100) are simply ignored rather than panicking.
+        assert!(!ids.contains(5));
+        assert!(!ids.reserve(5));
+        ids.dealloc(5);
+        ids.mark_external_id(5);
+
+        assert_eq!(ids.size(), 0);
+        assert_eq!(ids.alloc(), Some(1000));
+    }
+
+    #[test]
+    fn test_alloc_contiguous() {
+        let mut ids = SequentialIdAllocU8::<u8>::default();
+
+        assert_eq!(ids.alloc(), Some(0));
+        assert_eq!(ids.alloc_contiguous(4), Some(1));
+        assert!(ids.contains(1) && ids.contains(2) && ids.contains(3) && ids.contains(4));
+        assert_eq!(ids.size(), 5);
+
+        assert_eq!(ids.alloc(), Some(5));
+
+        ids.dealloc_contiguous(1, 4);
+        assert!(!ids.contains(1) && !ids.contains(2) && !ids.contains(3) && !ids.contains(4));
+        assert_eq!(ids.size(), 2);
+    }
+
+    #[test]
+    fn test_alloc_contiguous_wraps_like_alloc() {
+        let mut ids = SequentialIdAllocU8::<u8>::default();
+
+        for _ in 0..SequentialIdAllocU8::<u8>::max() {
+            assert!(ids.alloc().is_some());
+        }
+        ids.dealloc(0);
+        ids.dealloc(1);
+        ids.dealloc(2);
+
+        // next_ptr sits at $max, so the run behind it is only reachable by
+        // wrapping around, same as alloc() would.
+        assert_eq!(ids.alloc_contiguous(3), Some(0));
+    }
+
+    #[test]
+    fn test_dealloc_contiguous_large_n_does_not_overflow() {
+        let mut ids = SequentialIdAllocU8::<u8>::default();
+
+        assert_eq!(ids.alloc(), Some(0));
+        ids.dealloc_contiguous(0, usize::MAX);
+        assert!(!ids.contains(0));
+    }
+
+    #[test]
+    fn test_alloc_contiguous_no_run_available() {
+        let mut ids = SequentialIdAllocU8::<u8>::default();
+
+        for id in (0..=255u8).step_by(2) {
+            assert!(ids.reserve(id));
+        }
+
+        // Only isolated single free ids remain, so no run of 2 fits.
+        assert_eq!(ids.alloc_contiguous(2), None);
+    }
+
+    sequential_id_alloc_sync!(
+        SequentialIdAllocU8Sync,
+        SequentialIdAllocU8SyncInner,
+        u8,
+        256,
+        u8
+    );
+
+    #[test]
+    fn test_sync_alloc_across_threads() {
+        let ids = std::sync::Arc::new(SequentialIdAllocU8Sync::<u8>::default());
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let ids = std::sync::Arc::clone(&ids);
+                std::thread::spawn(move || (0..32).filter_map(|_| ids.alloc()).collect::<Vec<_>>())
+            })
+            .collect();
+
+        let mut allocated: Vec<u8> = handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect();
+
+        allocated.sort_unstable();
+        allocated.dedup();
+
+        // Every allocated id must be unique and the allocator must now be full.
+        assert_eq!(allocated.len(), 256);
+        assert!(ids.is_full());
+        assert_eq!(ids.alloc(), None);
+
+        ids.dealloc(10);
+        assert!(!ids.contains(10));
+        assert_eq!(ids.size(), 255);
+    }
+
+    #[test]
+    fn test_sync_has_full_allocator_parity() {
+        let ids = SequentialIdAllocU8Sync::<u8>::with_range(0);
+
+        assert!(ids.reserve(5));
+        assert!(!ids.reserve(5));
+        assert!(ids.contains(5));
+
+        assert_eq!(ids.alloc_contiguous(3), Some(0));
+        assert!(ids.contains(0) && ids.contains(1) && ids.contains(2));
+        ids.dealloc_contiguous(0, 3);
+        assert!(!ids.contains(0) && !ids.contains(1) && !ids.contains(2));
+
+        ids.mark_external_id(20);
+        assert!(ids.contains(20));
+
+        assert_eq!(ids.iter_allocated().collect::<Vec<_>>(), vec![5u8, 20]);
+        assert_eq!(ids.iter_free().take(3).collect::<Vec<_>>(), vec![0u8, 1, 2]);
+    }
+
+    #[test]
+    fn test_iter_allocated_and_free() {
+        let mut ids = SequentialIdAllocU8::<u8>::default();
+
+        assert_eq!(ids.alloc(), Some(0));
+        assert_eq!(ids.alloc(), Some(1));
+        assert_eq!(ids.alloc(), Some(2));
+        ids.dealloc(1);
+
+        assert_eq!(ids.iter_allocated().collect::<Vec<_>>(), vec![0u8, 2]);
+        assert_eq!(ids.iter_free().take(3).collect::<Vec<_>>(), vec![1u8, 3, 4]);
+    }
+
+    #[test]
+    fn test_iter_free_excludes_padding() {
+        // $max (10) is not a multiple of the u32 word width, so the padding
+        // bits beyond index 9 must never show up as free.
+        let ids = SequentialIdAllocU10::<u8>::default();
+        assert_eq!(ids.iter_free().count(), 10);
+    }
+
     #[test]
     fn test_arbitrary_inputs() {
         let alloc = std::cell::RefCell::new(SequentialIdAllocU8::default());